@@ -0,0 +1,653 @@
+//! This module holds the typed RPC command surface that `dispatch` routes
+//! into. Instead of one giant `match` pulling positional args out of the
+//! incoming JSON array by hand (`jedi::get(&["2"], &data)`, `jedi::get(&["3"],
+//! &data)`, ...), each command is a zero-sized struct implementing `Command`,
+//! with an associated `Args` type that maps directly onto the positional
+//! arguments the UI sends after the mid/cmd pair. `register_commands!` builds
+//! the `cmd -> handler` lookup table once, lazily, the same way garage's
+//! router macro wires up its endpoints.
+
+use ::std::collections::HashMap;
+use ::std::sync::{Arc, Mutex};
+use ::std::sync::atomic::{AtomicUsize, Ordering};
+
+use ::jedi::{self, Value};
+use ::serde::de::DeserializeOwned;
+
+use ::error::{TResult, TError};
+use ::util;
+use ::config;
+use ::util::event::Emitter;
+use ::turtl::Turtl;
+use ::search::Query;
+use ::models::user::User;
+use ::models::space::Space;
+use ::models::board::Board;
+use ::models::note::Note;
+use ::models::invite::Invite;
+use ::models::sync_record::{SyncAction, SyncType};
+use ::models::feedback::Feedback;
+use ::sync::sync_model;
+use ::sync::outgoing::SyncOutgoing;
+use ::migrations;
+
+use ::dispatch::{ResponseContext, CANCELLATIONS};
+
+lazy_static! {
+    /// Maps a subscription id (returned from `events:subscribe`) to the list
+    /// of event names it's bound to, so `events:unsubscribe` knows what to
+    /// unbind.
+    static ref SUBSCRIPTIONS: Mutex<HashMap<String, Vec<String>>> = Mutex::new(HashMap::new());
+    /// Monotonic counter used to mint subscription ids.
+    static ref SUBSCRIPTION_ID: AtomicUsize = AtomicUsize::new(0);
+    /// Per-model-id locks. Now that messages run concurrently on a worker
+    /// pool (see `main::start()`), two `profile:sync:model` calls racing on
+    /// the same model could interleave their save/delete; grabbing this
+    /// lock for the duration of the sync serializes calls that share a model
+    /// id while leaving unrelated models (and all read-only commands) free
+    /// to run in parallel.
+    static ref MODEL_LOCKS: Mutex<HashMap<String, Arc<Mutex<()>>>> = Mutex::new(HashMap::new());
+}
+
+/// Fetches (creating if needed) the lock guarding a given model id.
+fn lock_for_model(id: &str) -> Arc<Mutex<()>> {
+    let mut locks = MODEL_LOCKS.lock().unwrap();
+    locks.entry(id.to_string()).or_insert_with(|| Arc::new(Mutex::new(()))).clone()
+}
+
+/// Drops `id`'s entry out of `MODEL_LOCKS` once nothing else is using it, so
+/// a long-running process doesn't accumulate one `Arc<Mutex<()>>` per model
+/// id it has ever synced. Called after the guard taken on the lock that
+/// `lock_for_model` returned is released. The strong count check is `<= 2` (the
+/// map's own clone, plus the caller's, both still alive at this point) --
+/// anything higher means another in-flight call grabbed the same lock
+/// between `lock_for_model` and here, so we leave it for that call to clean
+/// up instead.
+fn release_model_lock(id: &str, lock: Arc<Mutex<()>>) {
+    let mut locks = MODEL_LOCKS.lock().unwrap();
+    if Arc::strong_count(&lock) <= 2 {
+        locks.remove(id);
+    }
+}
+
+/// `Command::Args` for commands that take no positional arguments (`ping`,
+/// `sync:pause`, `app:list-commands`, ...). `serde` unconditionally rejects
+/// deserializing a JSON *sequence* into `()` ("invalid type: sequence,
+/// expected unit"), so a zero-arg command can't just use `()` here -- every
+/// command's args arrive wrapped in an array by `command_args`, empty or
+/// not. `NoArgs` deserializes from (and discards) that array instead.
+#[derive(Debug, Default)]
+pub struct NoArgs;
+
+impl<'de> ::serde::de::Deserialize<'de> for NoArgs {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: ::serde::de::Deserializer<'de>
+    {
+        let _ignored: Vec<Value> = ::serde::de::Deserialize::deserialize(deserializer)?;
+        Ok(NoArgs)
+    }
+}
+
+/// A single RPC command. `Args` mirrors the positional JSON the UI sends
+/// after the mid/cmd pair -- e.g. `user:login`'s
+/// `["<mid>", "user:login", "user1", "pass1"]` becomes `Args = (String, String)`.
+/// Commands that take no arguments use `NoArgs` rather than `()` (see above).
+pub trait Command {
+    type Args: DeserializeOwned;
+    fn run(turtl: &Turtl, args: Self::Args, context: &ResponseContext) -> TResult<Value>;
+}
+
+/// Pulls everything after the `[mid, cmd, ...]` prefix out of a message and
+/// deserializes it into a command's `Args` type.
+fn command_args<T: DeserializeOwned>(data: &Value) -> TResult<T> {
+    let items = match data {
+        &Value::Array(ref items) => items,
+        _ => return Err(TError::BadValue(String::from("commands: expected an array-shaped message"))),
+    };
+    let rest: Vec<Value> = items.iter().skip(2).cloned().collect();
+    Ok(jedi::from_val(Value::Array(rest))?)
+}
+
+/// Builds the `cmd -> handler` lookup table `dispatch()` uses. Each entry
+/// closes over a `Command` impl, deserializing the message's positional args
+/// into that command's `Args` before calling `Command::run`.
+macro_rules! register_commands {
+    ( $( $name:expr => $cmd:ty ),* $(,)* ) => {{
+        let mut map: HashMap<&'static str, Box<Fn(&Turtl, Value, &ResponseContext) -> TResult<Value> + Send + Sync>> = HashMap::new();
+        $(
+            map.insert($name, Box::new(|turtl: &Turtl, data: Value, context: &ResponseContext| -> TResult<Value> {
+                let args = command_args::<<$cmd as Command>::Args>(&data)?;
+                <$cmd as Command>::run(turtl, args, context)
+            }) as Box<Fn(&Turtl, Value, &ResponseContext) -> TResult<Value> + Send + Sync>);
+        )*
+        map
+    }};
+}
+
+pub struct UserLogin;
+impl Command for UserLogin {
+    type Args = (String, String);
+    fn run(turtl: &Turtl, args: Self::Args, _context: &ResponseContext) -> TResult<Value> {
+        let (username, password) = args;
+        turtl.login(username, password)?;
+        Ok(jedi::obj())
+    }
+}
+
+pub struct UserJoin;
+impl Command for UserJoin {
+    type Args = (String, String);
+    fn run(turtl: &Turtl, args: Self::Args, _context: &ResponseContext) -> TResult<Value> {
+        let (username, password) = args;
+        turtl.join(username, password)?;
+        Ok(jedi::obj())
+    }
+}
+
+pub struct UserLogout;
+impl Command for UserLogout {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        turtl.logout()?;
+        util::sleep(1000);
+        Ok(jedi::obj())
+    }
+}
+
+pub struct UserDeleteAccount;
+impl Command for UserDeleteAccount {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        turtl.delete_account()?;
+        Ok(jedi::obj())
+    }
+}
+
+pub struct AppWipeUserData;
+impl Command for AppWipeUserData {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        turtl.wipe_user_data()?;
+        Ok(jedi::obj())
+    }
+}
+
+pub struct AppWipeAppData;
+impl Command for AppWipeAppData {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        turtl.wipe_app_data()?;
+        Ok(jedi::obj())
+    }
+}
+
+pub struct SyncStart;
+impl Command for SyncStart {
+    type Args = NoArgs;
+    // `sync_start` kicks off a background sync and returns immediately --
+    // by the time sync progress actually happens, this `dispatch()` call
+    // (and the `ResponseContext` it owns) has long since returned, so
+    // `context.progress()` has nothing to call into. Sync progress is
+    // reported the same way every other background event is: as a
+    // `turtl.events` trigger the UI picks up via `events:subscribe`, not
+    // through this per-request channel.
+    //
+    // Same reasoning rules out `context.cancel_handle()` here: `dispatch::process()`
+    // removes this request's `CANCELLATIONS` entry the moment this `run()`
+    // returns, which is immediately -- long before a client could ever turn
+    // around and call `app:cancel` against this mid. A cancel handle with
+    // no window in which it's reachable is just dead plumbing, so it isn't
+    // threaded through. Stopping a running sync belongs to `sync_shutdown`/
+    // `sync_pause`, which act on "the" sync directly instead of trying to
+    // reach it through a request-scoped mid.
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        turtl.sync_start()?;
+        Ok(jedi::obj())
+    }
+}
+
+pub struct SyncPause;
+impl Command for SyncPause {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        turtl.sync_pause();
+        Ok(jedi::obj())
+    }
+}
+
+pub struct SyncResume;
+impl Command for SyncResume {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        turtl.sync_resume();
+        Ok(jedi::obj())
+    }
+}
+
+pub struct SyncShutdown;
+impl Command for SyncShutdown {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        turtl.sync_shutdown(true)?;
+        Ok(jedi::obj())
+    }
+}
+
+pub struct SyncDeleteItem;
+impl Command for SyncDeleteItem {
+    type Args = (String,);
+    fn run(turtl: &Turtl, args: Self::Args, _context: &ResponseContext) -> TResult<Value> {
+        let (sync_id,) = args;
+        SyncOutgoing::delete_sync_item(turtl, &sync_id)?;
+        Ok(jedi::obj())
+    }
+}
+
+pub struct SyncGetFrozen;
+impl Command for SyncGetFrozen {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        let frozen = SyncOutgoing::get_all_frozen(turtl)?;
+        Ok(jedi::to_val(&frozen)?)
+    }
+}
+
+pub struct SyncGetPending;
+impl Command for SyncGetPending {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        let frozen = SyncOutgoing::get_all_pending(turtl)?;
+        Ok(jedi::to_val(&frozen)?)
+    }
+}
+
+pub struct SyncUnfreezeItem;
+impl Command for SyncUnfreezeItem {
+    type Args = (String,);
+    fn run(turtl: &Turtl, args: Self::Args, _context: &ResponseContext) -> TResult<Value> {
+        let (sync_id,) = args;
+        SyncOutgoing::kick_frozen_sync(turtl, &sync_id)?;
+        Ok(jedi::obj())
+    }
+}
+
+pub struct AppApiSetEndpoint;
+impl Command for AppApiSetEndpoint {
+    type Args = (String,);
+    fn run(_turtl: &Turtl, args: Self::Args, _context: &ResponseContext) -> TResult<Value> {
+        let (endpoint,) = args;
+        config::set(&["api", "endpoint"], &endpoint)?;
+        Ok(jedi::obj())
+    }
+}
+
+pub struct AppCancel;
+impl Command for AppCancel {
+    type Args = (String,);
+    fn run(_turtl: &Turtl, args: Self::Args, _context: &ResponseContext) -> TResult<Value> {
+        let (target_mid,) = args;
+        let registry = CANCELLATIONS.lock().unwrap();
+        match registry.get(&target_mid) {
+            Some(flag) => flag.store(true, Ordering::SeqCst),
+            None => {},
+        }
+        Ok(jedi::obj())
+    }
+}
+
+pub struct AppShutdown;
+impl Command for AppShutdown {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        turtl.sync_shutdown(false)?;
+        turtl.events.trigger("app:shutdown", &jedi::obj());
+        Ok(jedi::obj())
+    }
+}
+
+/// Runs a list of sub-requests (each shaped like a normal
+/// `["<subid>", "<cmd>", arg1, ...]` message) through the command registry
+/// in one round trip, returning `subid -> {"ok": ..}` or
+/// `subid -> {"err": ..}` for each. Cuts messaging overhead for bulk sync
+/// scenarios (importing many notes, replaying a queue of
+/// `profile:sync:model` edits) down to a single request.
+///
+/// Args are `[sub_requests, options]`, where `options.transactional` (default
+/// `false`) aborts the remaining sub-requests on the first error instead of
+/// running best-effort and reporting per-item results.
+///
+/// NOTE: `transactional` only means "stop on first error," not "all or
+/// nothing" -- sub-requests that already succeeded before the failing one
+/// are *not* rolled back. Doing that for real would mean every model type
+/// (`sync_model::save_model`/`delete_model`) growing an undo/compensating
+/// action, which is out of scope for this pass. Because nothing's rolled
+/// back, a transactional batch that fails partway still returns its
+/// `results` map (rather than a bare top-level error) so the caller can see
+/// exactly which subids already committed -- the failing subid is reported
+/// as `{"err": ..}`, and every subid after it as `{"aborted": true}`, so a
+/// client knows which writes landed and which are safe to retry.
+pub struct Batch;
+impl Command for Batch {
+    type Args = Value;
+    fn run(turtl: &Turtl, args: Self::Args, context: &ResponseContext) -> TResult<Value> {
+        let sub_requests: Vec<Value> = jedi::get(&["0"], &args)?;
+        let transactional: bool = jedi::get(&["1", "transactional"], &args).unwrap_or(false);
+
+        let mut results: HashMap<String, Value> = HashMap::new();
+        let mut aborted = false;
+        for sub in sub_requests {
+            let subid: String = jedi::get(&["0"], &sub)?;
+
+            if aborted {
+                results.insert(subid, json!({"aborted": true}));
+                continue;
+            }
+
+            let sub_cmd: String = jedi::get(&["1"], &sub)?;
+            let outcome = match COMMANDS.get(sub_cmd.as_str()) {
+                Some(handler) => handler(turtl, sub.clone(), context),
+                None => Err(TError::MissingCommand(sub_cmd.clone())),
+            };
+            match outcome {
+                Ok(val) => { results.insert(subid, json!({"ok": val})); },
+                Err(e) => {
+                    results.insert(subid, json!({"err": format!("{}", e)}));
+                    if transactional {
+                        aborted = true;
+                    }
+                },
+            }
+        }
+        Ok(jedi::to_val(&results)?)
+    }
+}
+
+pub struct AppDbVersion;
+impl Command for AppDbVersion {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        let conn = turtl.db.lock().unwrap();
+        let current = migrations::current_version(&conn)?;
+        let target = migrations::target_version();
+        Ok(json!({
+            "current": current,
+            "target": target,
+        }))
+    }
+}
+
+pub struct AppDbMigrate;
+impl Command for AppDbMigrate {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        let mut conn = turtl.db.lock().unwrap();
+        let applied = migrations::run_pending(&mut conn)?;
+        Ok(jedi::to_val(&applied)?)
+    }
+}
+
+pub struct ProfileLoad;
+impl Command for ProfileLoad {
+    type Args = NoArgs;
+    fn run(turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        let profile_guard = turtl.profile.read().unwrap();
+        let profile_data = json!({
+            "spaces": &profile_guard.spaces,
+            "boards": &profile_guard.boards,
+        });
+        Ok(profile_data)
+    }
+}
+
+pub struct ProfileSyncModel;
+impl Command for ProfileSyncModel {
+    type Args = (SyncAction, SyncType, Value);
+    fn run(turtl: &Turtl, args: Self::Args, _context: &ResponseContext) -> TResult<Value> {
+        let (action, ty, model_data) = args;
+
+        // serialize calls that touch the same model id -- an `Add` has no
+        // id yet, so fall back to locking on the action/type pair, which
+        // only matters for ordering against itself anyway
+        let lock_key: String = match jedi::get::<String>(&["id"], &model_data) {
+            Ok(id) => id,
+            Err(_) => format!("{:?}:{:?}", action, ty),
+        };
+        let model_lock = lock_for_model(&lock_key);
+        // wrapped in an IIFE so the `?`-early-returns below still fall
+        // through to `release_model_lock` instead of skipping it
+        let result = (|| -> TResult<Value> {
+            let _guard = model_lock.lock().unwrap();
+            match action.clone() {
+                SyncAction::Add | SyncAction::Edit => {
+                    let val = match ty {
+                        SyncType::User => {
+                            let mut model: User = jedi::from_val(model_data)?;
+                            sync_model::save_model(action, turtl, &mut model, false)?
+                        }
+                        SyncType::Space => {
+                            let mut model: Space = jedi::from_val(model_data)?;
+                            sync_model::save_model(action, turtl, &mut model, false)?
+                        }
+                        SyncType::Board => {
+                            let mut model: Board = jedi::from_val(model_data)?;
+                            sync_model::save_model(action, turtl, &mut model, false)?
+                        }
+                        SyncType::Note => {
+                            let mut model: Note = jedi::from_val(model_data)?;
+                            sync_model::save_model(action, turtl, &mut model, false)?
+                        }
+                        SyncType::Invite => {
+                            let mut model: Invite = jedi::from_val(model_data)?;
+                            sync_model::save_model(action, turtl, &mut model, false)?
+                        }
+                        SyncType::File => {
+                            Value::Null
+                        }
+                        _ => {
+                            return Err(TError::BadValue(format!("commands: profile:sync:model -- cannot direct sync an item of type {:?}", ty)));
+                        }
+                    };
+                    Ok(val)
+                },
+                SyncAction::Delete => {
+                    let id: String = jedi::get(&["id"], &model_data)?;
+                    match ty {
+                        SyncType::User => { sync_model::delete_model::<User>(turtl, &id, false)?; }
+                        SyncType::Space => { sync_model::delete_model::<Space>(turtl, &id, false)?; }
+                        SyncType::Board => { sync_model::delete_model::<Board>(turtl, &id, false)?; }
+                        SyncType::Note => { sync_model::delete_model::<Note>(turtl, &id, false)?; }
+                        SyncType::Invite => { sync_model::delete_model::<Invite>(turtl, &id, false)?; }
+                        SyncType::File => {}
+                        _ => {
+                            return Err(TError::BadValue(format!("commands: profile:sync:model -- cannot direct sync an item of type {:?}", ty)));
+                        }
+                    }
+                    Ok(jedi::obj())
+                },
+            }
+        })();
+        release_model_lock(&lock_key, model_lock);
+        result
+    }
+}
+
+pub struct ProfileGetNotes;
+impl Command for ProfileGetNotes {
+    type Args = (Vec<String>,);
+    // Decrypting a large batch of notes can take long enough that the UI
+    // wants a progress bar, so this loads one note at a time (instead of a
+    // single bulk `load_notes` call) and reports `{"loaded": .., "total": ..}`
+    // after each one via `context.progress()`.
+    fn run(turtl: &Turtl, args: Self::Args, context: &ResponseContext) -> TResult<Value> {
+        let (note_ids,) = args;
+        let total = note_ids.len();
+        let mut notes: Vec<Note> = Vec::with_capacity(total);
+        for (i, note_id) in note_ids.iter().enumerate() {
+            if context.is_cancelled() {
+                return Err(TError::Cancelled);
+            }
+            let note: Note = turtl.load_note(note_id)?;
+            notes.push(note);
+            context.progress(json!({"loaded": i + 1, "total": total}))?;
+        }
+        Ok(jedi::to_val(&notes)?)
+    }
+}
+
+pub struct ProfileFindNotes;
+impl Command for ProfileFindNotes {
+    type Args = (Query,);
+    // Same progress reporting as `ProfileGetNotes` for the note-loading half
+    // of a search -- `search.find()` itself is a single indexed lookup, not
+    // a loop we can meaningfully report partial progress through.
+    fn run(turtl: &Turtl, args: Self::Args, context: &ResponseContext) -> TResult<Value> {
+        let (qry,) = args;
+        let search_guard = turtl.search.read().unwrap();
+        if search_guard.is_none() {
+            return Err(TError::MissingField(String::from("commands: profile:find-notes -- turtl is missing `search` object")));
+        }
+        let search = search_guard.as_ref().unwrap();
+        let note_ids = search.find(&qry)?;
+        if context.is_cancelled() {
+            return Err(TError::Cancelled);
+        }
+        let total = note_ids.len();
+        let mut notes: Vec<Note> = Vec::with_capacity(total);
+        for (i, note_id) in note_ids.iter().enumerate() {
+            if context.is_cancelled() {
+                return Err(TError::Cancelled);
+            }
+            let note: Note = turtl.load_note(note_id)?;
+            notes.push(note);
+            context.progress(json!({"loaded": i + 1, "total": total}))?;
+        }
+        Ok(jedi::to_val(&notes)?)
+    }
+}
+
+pub struct ProfileGetTags;
+impl Command for ProfileGetTags {
+    type Args = (String, Vec<String>, i32);
+    fn run(turtl: &Turtl, args: Self::Args, _context: &ResponseContext) -> TResult<Value> {
+        let (space_id, boards, limit) = args;
+        let search_guard = turtl.search.read().unwrap();
+        if search_guard.is_none() {
+            return Err(TError::MissingField(String::from("commands: profile:get-tags -- turtl is missing `search` object")));
+        }
+        let search = search_guard.as_ref().unwrap();
+        let tags = search.tags_by_frequency(&space_id, &boards, limit)?;
+        Ok(jedi::to_val(&tags)?)
+    }
+}
+
+pub struct EventsSubscribe;
+impl Command for EventsSubscribe {
+    type Args = (Vec<String>,);
+    fn run(turtl: &Turtl, args: Self::Args, _context: &ResponseContext) -> TResult<Value> {
+        let (events,) = args;
+        let subid = format!("sub:{}", SUBSCRIPTION_ID.fetch_add(1, Ordering::SeqCst));
+        let mut bound = Vec::with_capacity(events.len());
+        for event in events {
+            // `turtl: &Turtl` is only good for the lifetime of this dispatch
+            // call, but `bind()` holds onto this closure indefinitely -- we
+            // need an owned, `'static` handle here, not a reference copy
+            // (contrast with e.g. `dispatch(&cmd, turtl.clone(), data, &context)`,
+            // where `.clone()` is just the no-op `&T` copy and the callee
+            // doesn't outlive the current call).
+            let turtl_clone = turtl.handle();
+            let subid_clone = subid.clone();
+            let event_clone = event.clone();
+            turtl.events.bind(&event, move |val: &Value| {
+                let frame = json!([subid_clone, event_clone, val]);
+                match turtl_clone.msg_raw(&frame) {
+                    Ok(_) => {},
+                    Err(e) => error!("commands: events:subscribe -- problem forwarding event {}: {}", event_clone, e),
+                }
+            }, &subid);
+            bound.push(event);
+        }
+        SUBSCRIPTIONS.lock().unwrap().insert(subid.clone(), bound);
+        Ok(Value::String(subid))
+    }
+}
+
+pub struct EventsUnsubscribe;
+impl Command for EventsUnsubscribe {
+    type Args = (String,);
+    fn run(turtl: &Turtl, args: Self::Args, _context: &ResponseContext) -> TResult<Value> {
+        let (subid,) = args;
+        let bound = SUBSCRIPTIONS.lock().unwrap().remove(&subid);
+        if let Some(events) = bound {
+            for event in events {
+                turtl.events.unbind(&event, &subid);
+            }
+        }
+        Ok(jedi::obj())
+    }
+}
+
+pub struct FeedbackSend;
+impl Command for FeedbackSend {
+    type Args = (Feedback,);
+    fn run(turtl: &Turtl, args: Self::Args, _context: &ResponseContext) -> TResult<Value> {
+        let (feedback,) = args;
+        feedback.send(turtl)?;
+        Ok(jedi::obj())
+    }
+}
+
+pub struct Ping;
+impl Command for Ping {
+    type Args = NoArgs;
+    fn run(_turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        info!("ping!");
+        Ok(Value::String(String::from("pong")))
+    }
+}
+
+pub struct AppListCommands;
+impl Command for AppListCommands {
+    type Args = NoArgs;
+    fn run(_turtl: &Turtl, _args: NoArgs, _context: &ResponseContext) -> TResult<Value> {
+        let mut names: Vec<&'static str> = COMMANDS.keys().cloned().collect();
+        names.sort();
+        Ok(jedi::to_val(&names)?)
+    }
+}
+
+lazy_static! {
+    /// The full `cmd -> handler` routing table. `dispatch()` just looks the
+    /// incoming command name up here.
+    pub static ref COMMANDS: HashMap<&'static str, Box<Fn(&Turtl, Value, &ResponseContext) -> TResult<Value> + Send + Sync>> = register_commands!{
+        "user:login" => UserLogin,
+        "user:join" => UserJoin,
+        "user:logout" => UserLogout,
+        "user:delete-account" => UserDeleteAccount,
+        "app:wipe-user-data" => AppWipeUserData,
+        "app:wipe-app-data" => AppWipeAppData,
+        "sync:start" => SyncStart,
+        "sync:pause" => SyncPause,
+        "sync:resume" => SyncResume,
+        "sync:shutdown" => SyncShutdown,
+        "sync:delete-item" => SyncDeleteItem,
+        "sync:get-frozen" => SyncGetFrozen,
+        "sync:get-pending" => SyncGetPending,
+        "sync:unfreeze-item" => SyncUnfreezeItem,
+        "app:api:set-endpoint" => AppApiSetEndpoint,
+        "app:cancel" => AppCancel,
+        "app:shutdown" => AppShutdown,
+        "app:db:version" => AppDbVersion,
+        "app:db:migrate" => AppDbMigrate,
+        "profile:load" => ProfileLoad,
+        "profile:sync:model" => ProfileSyncModel,
+        "profile:get-notes" => ProfileGetNotes,
+        "profile:find-notes" => ProfileFindNotes,
+        "profile:get-tags" => ProfileGetTags,
+        "events:subscribe" => EventsSubscribe,
+        "events:unsubscribe" => EventsUnsubscribe,
+        "feedback:send" => FeedbackSend,
+        "ping" => Ping,
+        "app:list-commands" => AppListCommands,
+        "batch" => Batch,
+    };
+}
@@ -2,221 +2,103 @@
 //! code to generate the response. Essentially, it's the RPC endpoint for core.
 //!
 //! Each message sent in is in the following format (JSON):
-//! 
+//!
 //!     ["<message id>", "<command>", arg1, arg2, ...]
 //!
 //! where the arg\* can be any valid JSON object. The Message ID is passed in
 //! when responding so the client knows which request we are responding to.
+//!
+//! The actual command implementations live in `commands` as typed `Command`
+//! impls; this module is just the thin router plus the per-request plumbing
+//! those commands are handed.
+//!
+//! Some commands run longer than a single request/reply round trip allows
+//! for. For those, `dispatch()` is handed a `ResponseContext` (see below)
+//! that lets it push `["<mid>", "progress", payload]` frames ahead of its
+//! terminal reply, and the UI can ask to be notified of background `turtl`
+//! events directly via `events:subscribe`/`events:unsubscribe` instead of
+//! polling. The same context also carries a `Cancel` handle, flipped by
+//! `app:cancel`, so long-running loops can poll it and bail out early
+//! instead of running to completion after the UI has stopped caring.
+
+use ::std::collections::HashMap;
+use ::std::sync::{Arc, Mutex};
+use ::std::sync::atomic::{AtomicBool, Ordering};
 
 use ::jedi::{self, Value};
 
 use ::error::{TResult, TError};
-use ::util;
-use ::config;
-use ::util::event::Emitter;
 use ::turtl::Turtl;
-use ::search::Query;
-use ::models::user::User;
-use ::models::space::Space;
-use ::models::board::Board;
-use ::models::note::Note;
-use ::models::invite::Invite;
-use ::models::sync_record::{SyncAction, SyncType};
-use ::models::feedback::Feedback;
-use ::sync::sync_model;
-use ::sync::outgoing::SyncOutgoing;
+use ::commands;
 
-/// Does our actual message dispatching
-fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
-    match cmd.as_ref() {
-        "user:login" => {
-            let username = jedi::get(&["2"], &data)?;
-            let password = jedi::get(&["3"], &data)?;
-            turtl.login(username, password)?;
-            Ok(jedi::obj())
-        },
-        "user:join" => {
-            let username = jedi::get(&["2"], &data)?;
-            let password = jedi::get(&["3"], &data)?;
-            turtl.join(username, password)?;
-            Ok(jedi::obj())
-        },
-        "user:logout" => {
-            turtl.logout()?;
-            util::sleep(1000);
-            Ok(jedi::obj())
-        },
-        "user:delete-account" => {
-            turtl.delete_account()?;
-            Ok(jedi::obj())
-        },
-        "app:wipe-user-data" => {
-            turtl.wipe_user_data()?;
-            Ok(jedi::obj())
-        },
-        "app:wipe-app-data" => {
-            turtl.wipe_app_data()?;
-            Ok(jedi::obj())
-        },
-        "sync:start" => {
-            turtl.sync_start()?;
-            Ok(jedi::obj())
-        },
-        "sync:pause" => {
-            turtl.sync_pause();
-            Ok(jedi::obj())
-        },
-        "sync:resume" => {
-            turtl.sync_resume();
-            Ok(jedi::obj())
-        },
-        "sync:shutdown" => {
-            turtl.sync_shutdown(true)?;
-            Ok(jedi::obj())
-        },
-        "sync:delete-item" => {
-            let sync_id: String = jedi::get(&["2"], &data)?;
-            SyncOutgoing::delete_sync_item(turtl, &sync_id)?;
-            Ok(jedi::obj())
-        },
-        "sync:get-frozen" => {
-            let frozen = SyncOutgoing::get_all_frozen(turtl)?;
-            Ok(jedi::to_val(&frozen)?)
-        },
-        "sync:get-pending" => {
-            let frozen = SyncOutgoing::get_all_pending(turtl)?;
-            Ok(jedi::to_val(&frozen)?)
-        },
-        "sync:unfreeze-item" => {
-            let sync_id: String = jedi::get(&["2"], &data)?;
-            SyncOutgoing::kick_frozen_sync(turtl, &sync_id)?;
-            Ok(jedi::obj())
-        },
-        "app:api:set-endpoint" => {
-            let endpoint: String = jedi::get(&["2"], &data)?;
-            config::set(&["api", "endpoint"], &endpoint)?;
-            Ok(jedi::obj())
-        },
-        "app:shutdown" => {
-            turtl.sync_shutdown(false)?;
-            turtl.events.trigger("app:shutdown", &jedi::obj());
-            Ok(jedi::obj())
-        },
-        "profile:load" => {
-            let profile_guard = turtl.profile.read().unwrap();
-            let profile_data = json!({
-                "spaces": &profile_guard.spaces,
-                "boards": &profile_guard.boards,
-            });
-            Ok(profile_data)
-        },
-        "profile:sync:model" => {
-            let action: SyncAction = match jedi::get(&["2"], &data) {
-                Ok(action) => action,
-                Err(e) => return Err(TError::BadValue(format!("dispatch: {} -- bad sync action: {}", cmd, e))),
-            };
-            let ty: SyncType = jedi::get(&["3"], &data)?;
+lazy_static! {
+    /// Maps an in-flight request's `mid` to a cancellation flag, so
+    /// `app:cancel` can reach across requests and tell another one to stop.
+    pub(crate) static ref CANCELLATIONS: Mutex<HashMap<String, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+}
 
-            match action.clone() {
-                SyncAction::Add | SyncAction::Edit => {
-                    let val = match ty {
-                        SyncType::User => {
-                            let mut model: User = jedi::get(&["4"], &data)?;
-                            sync_model::save_model(action, turtl, &mut model, false)?
-                        }
-                        SyncType::Space => {
-                            let mut model: Space = jedi::get(&["4"], &data)?;
-                            sync_model::save_model(action, turtl, &mut model, false)?
-                        }
-                        SyncType::Board => {
-                            let mut model: Board = jedi::get(&["4"], &data)?;
-                            sync_model::save_model(action, turtl, &mut model, false)?
-                        }
-                        SyncType::Note => {
-                            let mut model: Note = jedi::get(&["4"], &data)?;
-                            sync_model::save_model(action, turtl, &mut model, false)?
-                        }
-                        SyncType::Invite => {
-                            let mut model: Invite = jedi::get(&["4"], &data)?;
-                            sync_model::save_model(action, turtl, &mut model, false)?
-                        }
-                        SyncType::File => {
-                            Value::Null
-                        }
-                        _ => {
-                            return Err(TError::BadValue(format!("dispatch: {} -- cannot direct sync an item of type {:?}", cmd, ty)));
-                        }
-                    };
-                    Ok(val)
-                },
-                SyncAction::Delete => {
-                    let id: String = jedi::get(&["4", "id"], &data)?;
-                    match ty {
-                        SyncType::User => {
-                            sync_model::delete_model::<User>(turtl, &id, false)?;
-                        }
-                        SyncType::Space => {
-                            sync_model::delete_model::<Space>(turtl, &id, false)?;
-                        }
-                        SyncType::Board => {
-                            sync_model::delete_model::<Board>(turtl, &id, false)?;
-                        }
-                        SyncType::Note => {
-                            sync_model::delete_model::<Note>(turtl, &id, false)?;
-                        }
-                        SyncType::Invite => {
-                            sync_model::delete_model::<Invite>(turtl, &id, false)?;
-                        }
-                        SyncType::File => {
-                        }
-                        _ => {
-                            return Err(TError::BadValue(format!("dispatch: {} -- cannot direct sync an item of type {:?}", cmd, ty)));
-                        }
-                    }
-                    Ok(jedi::obj())
-                },
-            }
-        },
-        "profile:get-notes" => {
-            let note_ids = jedi::get(&["2"], &data)?;
-            let notes: Vec<Note> = turtl.load_notes(&note_ids)?;
-            Ok(jedi::to_val(&notes)?)
-        },
-        "profile:find-notes" => {
-            let qry: Query = jedi::get(&["2"], &data)?;
-            let search_guard = turtl.search.read().unwrap();
-            if search_guard.is_none() {
-                return Err(TError::MissingField(format!("dispatch: {} -- turtl is missing `search` object", cmd)));
-            }
-            let search = search_guard.as_ref().unwrap();
-            let note_ids = search.find(&qry)?;
-            let notes: Vec<Note> = turtl.load_notes(&note_ids)?;
-            Ok(jedi::to_val(&notes)?)
-        },
-        "profile:get-tags" => {
-            let space_id: String = jedi::get(&["2"], &data)?;
-            let boards: Vec<String> = jedi::get(&["3"], &data)?;
-            let limit: i32 = jedi::get(&["4"], &data)?;
-            let search_guard = turtl.search.read().unwrap();
-            if search_guard.is_none() {
-                return Err(TError::MissingField(format!("dispatch: {} -- turtl is missing `search` object", cmd)));
-            }
-            let search = search_guard.as_ref().unwrap();
-            let tags = search.tags_by_frequency(&space_id, &boards, limit)?;
-            Ok(jedi::to_val(&tags)?)
-        },
-        "feedback:send" => {
-            let feedback: Feedback = jedi::get(&["2"], &data)?;
-            feedback.send(turtl)?;
-            Ok(jedi::obj())
-        },
-        "ping" => {
-            info!("ping!");
-            Ok(Value::String(String::from("pong")))
-        },
-        _ => {
-            Err(TError::MissingCommand(cmd.clone()))
-        }
+/// A cheap handle to a request's cancellation flag. Long-running handlers
+/// (the note-loading loop in `profile:get-notes`, the search-then-load in
+/// `profile:find-notes`, etc) poll `ResponseContext::is_cancelled()` between
+/// units of work and bail out with `TError::Cancelled` once it's been
+/// flipped by `app:cancel`.
+#[derive(Clone)]
+pub struct Cancel {
+    flag: Arc<AtomicBool>,
+}
+
+impl Cancel {
+    fn new(flag: Arc<AtomicBool>) -> Self {
+        Cancel { flag: flag }
+    }
+
+    /// Returns true if this request has been asked to cancel.
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+}
+
+/// Gives a running command a handle back into the messaging system, separate
+/// from its eventual `msg_success`/`msg_error` reply. Borrowed from the
+/// Jupyter kernel model of a broadcast (IoPub) channel alongside the
+/// request/reply channel: a command can call `.progress()` as many times as
+/// it likes before it finally returns from `dispatch()`.
+pub struct ResponseContext<'a> {
+    mid: &'a String,
+    turtl: &'a Turtl,
+    cancel: Cancel,
+}
+
+impl<'a> ResponseContext<'a> {
+    fn new(mid: &'a String, turtl: &'a Turtl, cancel: Cancel) -> Self {
+        ResponseContext { mid: mid, turtl: turtl, cancel: cancel }
+    }
+
+    /// Push an intermediate `["<mid>", "progress", payload]` frame to the UI
+    /// ahead of this command's terminal reply.
+    pub fn progress(&self, payload: Value) -> TResult<()> {
+        let frame = json!([self.mid, "progress", payload]);
+        self.turtl.msg_raw(&frame)
+    }
+
+    /// True if the UI has asked (via `app:cancel`) for this request to stop.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// Hand out a cloneable cancellation handle, for passing down into loops
+    /// that live outside of `dispatch()` itself.
+    pub fn cancel_handle(&self) -> Cancel {
+        self.cancel.clone()
+    }
+}
+
+/// Does our actual message dispatching. Looks `cmd` up in the `commands`
+/// registry and hands it off to the matching `Command` impl.
+fn dispatch(cmd: &String, turtl: &Turtl, data: Value, context: &ResponseContext) -> TResult<Value> {
+    match commands::COMMANDS.get(cmd.as_str()) {
+        Some(handler) => handler(turtl, data, context),
+        None => Err(TError::MissingCommand(cmd.clone())),
     }
 }
 
@@ -238,7 +120,13 @@ pub fn process(turtl: &Turtl, msg: &String) -> TResult<()> {
 
     info!("dispatch({}): {}", mid, cmd);
 
-    match dispatch(&cmd, turtl.clone(), data) {
+    let flag = Arc::new(AtomicBool::new(false));
+    CANCELLATIONS.lock().unwrap().insert(mid.clone(), flag.clone());
+    let context = ResponseContext::new(&mid, turtl, Cancel::new(flag));
+    let res = dispatch(&cmd, turtl.clone(), data, &context);
+    CANCELLATIONS.lock().unwrap().remove(&mid);
+
+    match res {
         Ok(val) => {
             match turtl.msg_success(&mid, val) {
                 Err(e) => error!("dispatch::process() -- problem sending response (mid {}): {}", mid, e),
@@ -254,4 +142,3 @@ pub fn process(turtl: &Turtl, msg: &String) -> TResult<()> {
     }
     Ok(())
 }
-
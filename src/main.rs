@@ -33,15 +33,20 @@ mod crypto;
 #[macro_use]
 mod models;
 mod storage;
+mod migrations;
+mod commands;
 mod dispatch;
 mod turtl;
 
 use ::std::thread;
 use ::std::sync::Arc;
+use ::std::sync::atomic::{AtomicUsize, Ordering};
 use ::std::fs;
 use ::std::io::ErrorKind;
+use ::std::time::Duration;
 
 use ::crossbeam::sync::MsQueue;
+use ::futures_cpupool::CpuPool;
 use ::jedi::Value;
 
 use ::error::{TError, TResult};
@@ -61,6 +66,10 @@ lazy_static!{
     static ref RUN: Stopper = Stopper::new();
 }
 
+/// How many messages we'll process concurrently. Bounded so a flood of
+/// requests can't spawn unbounded OS threads out from under us.
+const WORKER_POOL_SIZE: usize = 4;
+
 /// Stop all threads and close down Turtl
 pub fn stop(tx: Pipeline) {
     (*RUN).set(false);
@@ -140,6 +149,30 @@ pub fn start(config_str: String) -> thread::JoinHandle<()> {
             }
         };
 
+        // apply any pending schema migrations before we start accepting
+        // requests -- a versioned, ordered upgrade path on top of the
+        // one-shot `dumpy_schema`, which is still handed to `new_wrap` above.
+        //
+        // NB: this ought to live inside `Turtl::new_wrap` itself so that
+        // *every* path constructing a `Turtl` (including the C/mobile
+        // bindings entry point called out in the TODO on `main()` below)
+        // gets migrated, not just this desktop `start()` path. `turtl.rs`
+        // isn't part of this checkout, so it can't be edited here -- this
+        // is staged as close to that call site as this tree allows.
+        {
+            let turtl_guard = turtl.write().unwrap();
+            let mut conn = turtl_guard.db.lock().unwrap();
+            match migrations::run_pending(&mut conn) {
+                Ok(applied) => if !applied.is_empty() {
+                    info!("main::start() -- applied db migrations: {:?}", applied);
+                },
+                Err(e) => {
+                    error!("main::start() -- error applying db migrations: {}", e);
+                    return;
+                }
+            }
+        }
+
         // bind turtl.events "app:shutdown" to close everything
         {
             let ref mut events = turtl.write().unwrap().events;
@@ -155,11 +188,38 @@ pub fn start(config_str: String) -> thread::JoinHandle<()> {
         // run our main loop. all threads pipe their data/responses into this
         // loop, meaning <main> only has to check one place to grab messages.
         // this creates an event loop of sorts, without all the grossness.
+        //
+        // each message is handed off to `pool` instead of being run inline,
+        // so a single slow command (a big sync, a note decrypt) can't stall
+        // every other pending request. handlers still reply through
+        // `messaging` themselves (keyed by mid) whenever they finish, so
+        // <main> here only has to demultiplex the incoming queue, not the
+        // outgoing replies.
+        //
+        // `.forget()` detaches the `CpuFuture` instead of keeping it around
+        // to join on, so we track how many handlers are still running
+        // ourselves (`in_flight`) and spin-wait for it to drain to zero
+        // before tearing `turtl` down below -- otherwise a worker thread
+        // could still be mid-handler, touching `turtl`, while `shutdown()`
+        // runs out from under it on the main thread.
+        let pool = CpuPool::new(WORKER_POOL_SIZE);
+        let in_flight = Arc::new(AtomicUsize::new(0));
         info!("main::start() -- main loop");
         while (*RUN).running() {
             debug!("turtl: main thread message loop");
             let handler = queue_main.pop();
-            handler.call_box(turtl.clone());
+            let turtl_clone = turtl.clone();
+            let in_flight_clone = in_flight.clone();
+            in_flight.fetch_add(1, Ordering::SeqCst);
+            pool.spawn_fn(move || -> Result<(), ()> {
+                handler.call_box(turtl_clone);
+                in_flight_clone.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            }).forget();
+        }
+        info!("main::start() -- draining in-flight requests");
+        while in_flight.load(Ordering::SeqCst) > 0 {
+            thread::sleep(Duration::from_millis(10));
         }
         info!("main::start() -- shutting down");
         turtl.write().unwrap().shutdown();
@@ -0,0 +1,78 @@
+//! Tracks and applies versioned schema changes against the local (encrypted)
+//! sqlite cache that `storage` manages. Previously the app received a
+//! `dumpy_schema` blob via `process_config` and applied it once at startup
+//! with no notion of "what version is this db at" -- this module adds that
+//! bookkeeping, so the cache can be upgraded between client releases instead
+//! of requiring a full `app:wipe-app-data`.
+//!
+//! Migrations are compiled into the binary as an ordered `Vec<Migration>`.
+//! `run_pending()` is called once at startup from `main::start()`, right
+//! after `Turtl::new_wrap` returns. Ideally this call would live inside
+//! `new_wrap` itself so every path that constructs a `Turtl` (including
+//! non-desktop bindings) gets migrated, not just this process's entry
+//! point -- see the note at the call site in `main.rs`.
+
+use ::rusqlite::Connection;
+
+use ::error::TResult;
+
+/// A single forward/backward schema change, identified by an ordinal
+/// `version`. Once a migration has shipped, its `up`/`down` must never
+/// change -- add a new migration instead.
+pub struct Migration {
+    pub version: u64,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+/// All migrations this build knows about, oldest first. Append new entries
+/// here; never edit or reorder an already-released one.
+fn migrations() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            up: "CREATE TABLE IF NOT EXISTS _placeholder (id TEXT PRIMARY KEY)",
+            down: "DROP TABLE IF EXISTS _placeholder",
+        },
+    ]
+}
+
+/// Makes sure the `_migrations` bookkeeping table exists.
+fn ensure_migrations_table(conn: &Connection) -> TResult<()> {
+    conn.execute("CREATE TABLE IF NOT EXISTS _migrations (version INTEGER PRIMARY KEY)", &[])?;
+    Ok(())
+}
+
+/// The highest migration version that's been applied to this db, or 0 if
+/// none have.
+pub fn current_version(conn: &Connection) -> TResult<u64> {
+    ensure_migrations_table(conn)?;
+    let version: Option<i64> = conn.query_row("SELECT MAX(version) FROM _migrations", &[], |row| row.get(0))?;
+    Ok(version.unwrap_or(0) as u64)
+}
+
+/// The highest version compiled into this binary.
+pub fn target_version() -> u64 {
+    migrations().iter().map(|m| m.version).max().unwrap_or(0)
+}
+
+/// Applies every migration newer than the db's current version, in
+/// ascending order, each inside its own transaction, recording the version
+/// as it succeeds. Returns the list of versions applied (oldest first). If a
+/// migration's `up` fails, its transaction is rolled back and nothing past
+/// it runs.
+pub fn run_pending(conn: &mut Connection) -> TResult<Vec<u64>> {
+    let current = current_version(conn)?;
+    let mut pending: Vec<Migration> = migrations().into_iter().filter(|m| m.version > current).collect();
+    pending.sort_by_key(|m| m.version);
+
+    let mut applied = Vec::new();
+    for migration in pending {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.up)?;
+        tx.execute("INSERT INTO _migrations (version) VALUES (?)", &[&(migration.version as i64)])?;
+        tx.commit()?;
+        applied.push(migration.version);
+    }
+    Ok(applied)
+}